@@ -1,22 +1,125 @@
 // https://blog.logrocket.com/a-practical-guide-to-async-in-rust/
 use tokio::task;
+use tokio::time::timeout;
 use log::*;
+use std::fmt;
 use std::io::Write;
-use futures::future::join_all;
+use std::time::Duration;
+use futures::stream::{self, StreamExt};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Cancellation is cooperative: a future only notices `token.cancelled()` at
+// its next `.await` point, so a request already inside `spawn_blocking`
+// (e.g. `analyze`) will run to completion regardless. Callers that aggregate
+// over many cancellable requests should expect a mix of `Ok`s and
+// `Cancelled`s once a token fires, not a clean all-or-nothing stop.
+#[derive(Debug)]
+enum AppError {
+    Cancelled,
+    Timeout,
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Cancelled => write!(f, "request cancelled"),
+            AppError::Timeout => write!(f, "request timed out"),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+// Controls how a failed `fetch` is retried: `max_attempts` total tries
+// (including the first), a `base_delay` that doubles every attempt, and a
+// `max_delay` ceiling so the backoff doesn't grow unbounded against a
+// persistently flaky endpoint.
+#[derive(Debug, Clone, Copy)]
+struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+}
+
+impl RetryPolicy {
+    fn delay_for(&self, attempt: u32) -> Duration {
+        let scaled = self.base_delay.saturating_mul(2u32.saturating_pow(attempt));
+        let capped = std::cmp::min(scaled, self.max_delay);
+
+        // A little jitter keeps many simultaneously-retrying requests from
+        // all waking back up on the same tick.
+        let jitter_nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+        let factor = 0.75 + (jitter_nanos % 500) as f64 / 1000.0; // 0.75x - 1.25x
+        capped.mul_f64(factor)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(5),
+        }
+    }
+}
+
+// Fetches `slowly(1000)` with a per-attempt timeout, retrying transient
+// failures (timeouts, connection errors, 5xx) with exponential backoff up to
+// `policy.max_attempts`. A 4xx response is treated as permanent and returned
+// immediately without consuming further attempts.
+async fn fetch(token: &CancellationToken, policy: &RetryPolicy) -> Result<reqwest::Response> {
+    let mut attempt = 0;
+    loop {
+        let outcome: std::result::Result<reqwest::Response, (bool, Box<dyn std::error::Error + Send + Sync>)> = tokio::select! {
+            res = timeout(REQUEST_TIMEOUT, reqwest::get(slowly(1000))) => match res {
+                Ok(Ok(response)) => match response.error_for_status() {
+                    Ok(response) => Ok(response),
+                    Err(e) => {
+                        let retryable = e.status().map_or(true, |s| s.is_server_error());
+                        Err((retryable, e.into()))
+                    }
+                },
+                Ok(Err(e)) => Err((true, e.into())),
+                Err(_) => Err((true, AppError::Timeout.into())),
+            },
+            _ = token.cancelled() => return Err(AppError::Cancelled.into()),
+        };
+
+        match outcome {
+            Ok(response) => return Ok(response),
+            Err((retryable, err)) => {
+                attempt += 1;
+                if !retryable || attempt >= policy.max_attempts {
+                    return Err(err);
+                }
+                let delay = policy.delay_for(attempt - 1);
+                warn!("request failed ({}), retrying (attempt {} of {}) in {:?}", err, attempt + 1, policy.max_attempts, delay);
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+}
+
 // simple concurrent calls - start
-async fn request(n: usize) -> Result<()> {
-    reqwest::get(slowly(1000)).await?;
+async fn request(n: usize, token: CancellationToken, policy: RetryPolicy) -> Result<()> {
+    fetch(&token, &policy).await?;
     info!("got response {}", n);
     Ok(())
 }
 
-async fn app() -> Result<()> {
+async fn app(token: CancellationToken, policy: RetryPolicy) -> Result<()> {
     // treat this as the main function of the async part of the program
-    let resp1 = task::spawn(request(1));
-    let resp2 = task::spawn(request(2));
+    let resp1 = task::spawn(request(1, token.clone(), policy));
+    let resp2 = task::spawn(request(2, token.clone(), policy));
 
     let _ = resp1.await??;
     let _ = resp2.await??;
@@ -33,44 +136,192 @@ fn slowly(delay_ms: u32) -> reqwest::Url {
 // simple concurrent calls - end
 
 
-async fn app_cpu_intensive() -> Result<()> {
-    let mut futures = vec![];
-    for i in 1..=10 {
-        let fut = task::spawn(get_and_analyze(i));
-        futures.push(fut);
-    }
-
-    let results = join_all(futures).await;
-
+// Drive `count` datasets through `get_and_analyze`, but never have more than
+// `concurrency` requests in flight at once. `buffer_unordered` keeps polling
+// the next queued future as soon as a slot frees up, so peak in-flight work
+// stays bounded regardless of how large `count` gets. Totals are folded in as
+// each result arrives rather than collected into a `Vec` first, so memory
+// stays O(1) in `count` and a partial ratio is visible every `log_every`
+// completions instead of only once the whole batch is done.
+async fn app_cpu_intensive(
+    count: usize,
+    concurrency: usize,
+    log_every: usize,
+    token: CancellationToken,
+    policy: RetryPolicy,
+) -> Result<()> {
     let mut total_ones = 0;
     let mut total_zeroes = 0;
+    let mut completed = 0;
+
+    let mut results = stream::iter(1..=count)
+        .map(|i| get_and_analyze(i, token.clone(), policy))
+        .buffer_unordered(concurrency);
 
-    for result in results {
-        // `spawn_blocking` returns a `JoinResult` we need to unwrap first
-        let ones_res: Result<(u64, u64)> = result?;
-        let (ones, zeroes) = ones_res?;
+    // Once cancellation fires, in-flight requests start returning `Cancelled`
+    // at their next await point rather than all at once, so we stop
+    // aggregating on the first such error but keep whatever totals we
+    // already folded in instead of discarding the partial batch.
+    while let Some(result) = results.next().await {
+        match result {
+            Ok((ones, zeroes)) => {
+                total_ones += ones;
+                total_zeroes += zeroes;
+                completed += 1;
 
-        total_ones += ones;
-        total_zeroes += zeroes;
+                if log_every > 0 && completed % log_every == 0 {
+                    info!(
+                        "Partial ratio after {} datasets: {:.02}",
+                        completed,
+                        total_ones as f64 / total_zeroes as f64
+                    );
+                }
+            }
+            Err(e) => {
+                warn!("stopping early after {} datasets: {}", completed, e);
+                break;
+            }
+        }
     }
 
     info!("Ratio of ones/zeros: {:.02}",total_ones as f64 / total_zeroes as f64);
     Ok(())
 }
 
-async fn get_and_analyze(n: usize) -> Result<(u64, u64)> {
-    let response: reqwest::Response = reqwest::get(slowly(1000)).await?;
+async fn get_and_analyze(n: usize, token: CancellationToken, policy: RetryPolicy) -> Result<(u64, u64)> {
+    let response = fetch(&token, &policy).await?;
     info!("Dataset {}", n);
 
-    let txt = response.text().await?;
+    let txt = tokio::select! {
+        res = response.text() => res?,
+        _ = token.cancelled() => return Err(AppError::Cancelled.into()),
+    };
 
     // We send our analysis work to a thread where there is no runtime running
-    // so we don't block the runtime by analyzing the data
+    // so we don't block the runtime by analyzing the data. Once spawned, this
+    // pass cannot be cancelled mid-way; it always runs to completion.
     let res = task::spawn_blocking(move || analyze(&txt)).await?;
     info!("Processed {}", n);
     Ok(res)
 }
 
+// supervisor loop - start
+
+// Cadences for the jobs `run_supervisor` interleaves.
+#[derive(Debug, Clone, Copy)]
+struct SupervisorConfig {
+    fetch_interval: Duration,
+    stats_interval: Duration,
+}
+
+// Runs several recurring jobs on independent cadences inside a single
+// `select!` loop so a slow job never stalls a faster one's schedule. The
+// dataset fetch does a real (~1000ms) await, so its body is `task::spawn`ed
+// off the loop and reports back over a channel; the stats tick, by
+// contrast, is cheap enough to run inline. Because the fetch runs in its own
+// task, a fetch that runs long does not hold back either timer. Both timers
+// use `MissedTickBehavior::Delay`, so a tick that comes due while the loop is
+// busy slides the schedule rather than firing a burst of catch-up ticks.
+async fn run_supervisor(
+    config: SupervisorConfig,
+    token: CancellationToken,
+    policy: RetryPolicy,
+) -> Result<()> {
+    let mut fetch_tick = tokio::time::interval(config.fetch_interval);
+    fetch_tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut stats_tick = tokio::time::interval(config.stats_interval);
+    stats_tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    let (result_tx, mut result_rx) = mpsc::unbounded_channel();
+    let mut total_ones = 0u64;
+    let mut total_zeroes = 0u64;
+    let mut dataset_id = 0usize;
+
+    loop {
+        tokio::select! {
+            _ = fetch_tick.tick() => {
+                dataset_id += 1;
+                let id = dataset_id;
+                let token = token.clone();
+                let tx = result_tx.clone();
+                task::spawn(async move {
+                    match get_and_analyze(id, token, policy).await {
+                        Ok(res) => { let _ = tx.send(res); }
+                        Err(e) => warn!("supervisor fetch {} failed: {}", id, e),
+                    }
+                });
+            }
+            Some((ones, zeroes)) = result_rx.recv() => {
+                total_ones += ones;
+                total_zeroes += zeroes;
+            }
+            _ = stats_tick.tick() => {
+                info!(
+                    "supervisor stats: ratio of ones/zeros so far {:.02}",
+                    total_ones as f64 / total_zeroes as f64
+                );
+            }
+            _ = token.cancelled() => {
+                info!("supervisor received shutdown signal");
+                return Ok(());
+            }
+        }
+    }
+}
+// supervisor loop - end
+
+// service - start
+
+// A join handle for a `Service` running on its own background runtime.
+// `shutdown()` signals the supervisor loop to stop; `wait()` parks the
+// calling thread until the runtime has fully drained and exited.
+struct ServiceHandle {
+    token: CancellationToken,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl ServiceHandle {
+    fn shutdown(&self) {
+        self.token.cancel();
+    }
+
+    fn wait(mut self) {
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// Lets a synchronous caller embed this crate's async workload without
+// writing their own async `main`: `start()` builds a multi-thread runtime on
+// a background OS thread, runs `run_supervisor` on it, and hands back a
+// `ServiceHandle` for a start -> run in background -> signal -> join
+// lifecycle instead of a single `block_on` that owns the calling thread for
+// the whole program.
+struct Service;
+
+impl Service {
+    fn start(config: SupervisorConfig, policy: RetryPolicy) -> ServiceHandle {
+        let token = CancellationToken::new();
+        let run_token = token.clone();
+
+        let thread = std::thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().expect("failed to build service runtime");
+            rt.block_on(async {
+                if let Err(e) = run_supervisor(config, run_token, policy).await {
+                    error!("service exited with error: {}", e);
+                }
+            });
+        });
+
+        ServiceHandle {
+            token,
+            thread: Some(thread),
+        }
+    }
+}
+// service - end
+
 // Now we want to both fetch some data and do some CPU intensive analysis on it
 fn analyze(txt: &str) -> (u64, u64) {
     let txt = txt.as_bytes();
@@ -94,10 +345,12 @@ fn main() {
     }).init();
 
     let rt = tokio::runtime::Runtime::new().unwrap();
+    let token = CancellationToken::new();
+    let retry_policy = RetryPolicy::default();
 
     //  simple concurrent calls start
     info!("starting simple concurrent program");
-    match rt.block_on(app()) {
+    match rt.block_on(app(token.clone(), retry_policy)) {
         Ok(_) => info!("Done"),
         Err(e) => error!("Error {}", e),
     };
@@ -106,10 +359,23 @@ fn main() {
 
     //  cpu intensive concurrent calls start
     info!("starting cpu intensive concurrent program");
-    match rt.block_on(app_cpu_intensive()) {
+    match rt.block_on(app_cpu_intensive(10, 4, 3, token, retry_policy)) {
         Ok(_) => info!("Done"),
         Err(e) => error!("Error {}", e),
     };
     info!("finished concurrent program");
     //  cpu intensive concurrent calls end
+
+    //  supervisor service start
+    info!("starting supervisor service");
+    let supervisor_config = SupervisorConfig {
+        fetch_interval: Duration::from_secs(2),
+        stats_interval: Duration::from_secs(5),
+    };
+    let service = Service::start(supervisor_config, retry_policy);
+    std::thread::sleep(Duration::from_secs(11));
+    service.shutdown();
+    service.wait();
+    info!("finished supervisor service");
+    //  supervisor service end
 }